@@ -0,0 +1,315 @@
+//! A lobby server for the rock-paper-scissor game.
+//!
+//! Unlike `src/main.rs`, which hard-codes exactly two peers, this
+//! server accepts any number of TCP connections, tracks who is
+//! connected in a `HashMap<String, PlayerHandle>` keyed by the `name`
+//! from `Message::Hello`, and pairs up waiting players into matches.
+//!
+//! Each connection is handled by its own `handle_client` task, which
+//! only ever talks to the central `run_lobby` task through an mpsc
+//! channel. That channel is how moves get forwarded between the two
+//! clients of a match, and how `Message::Leave` (or a plain
+//! disconnect) gets noticed and reported to the other side -- the
+//! same fan-in/fan-out shape a chat server uses to relay messages
+//! between many connections.
+
+use clap::Parser;
+use rock_paper_scissor::message::{Action, Message};
+use rock_paper_scissor::transport::FramedTcp;
+use rock_paper_scissor::utils::{recv_msg, send_msg};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// A lobby server that matches up rock-paper-scissor players.
+#[derive(Debug, Clone, Parser)]
+struct Args {
+    /// The address the server listens on, e.g. "127.0.0.1:9000".
+    pub bind_addr: SocketAddr,
+}
+
+/// A per-player handle the lobby uses to forward a message to that
+/// player's `handle_client` task, which writes it out over the
+/// socket.
+struct PlayerHandle {
+    outbox: mpsc::Sender<Message>,
+}
+
+/// The events a connected client's task reports to the lobby.
+enum LobbyEvent {
+    /// The client said hello and is ready to be paired up.
+    Join {
+        name: String,
+        outbox: mpsc::Sender<Message>,
+    },
+    /// The client played a move.
+    Act { name: String, action: Action },
+    /// The client left, either by sending `Message::Leave` or by
+    /// disconnecting.
+    Leave { name: String },
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> io::Result<()> {
+    let Args { bind_addr } = Args::parse();
+
+    let listener = TcpListener::bind(bind_addr).await?;
+    println!("Lobby server listening on {bind_addr}");
+
+    // The lobby task owns all of the shared state below (`players`,
+    // who is waiting, who is matched against whom). Every
+    // `handle_client` task only ever reaches it through `lobby_tx`, so
+    // there is no `Mutex` to take: the lobby task is the only place
+    // that ever touches that state.
+    let (lobby_tx, lobby_rx) = mpsc::channel(32);
+    tokio::spawn(run_lobby(lobby_rx));
+
+    loop {
+        let (stream, peer_addr) = listener.accept().await?;
+        let lobby_tx = lobby_tx.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = handle_client(stream, lobby_tx).await {
+                eprintln!("Connection from {peer_addr} failed: {err}");
+            }
+        });
+    }
+}
+
+/// Handles one client connection for its whole lifetime: reads
+/// `Message`s off the socket and reports them to the lobby, while
+/// concurrently writing out whatever the lobby forwards back to this
+/// player over `outbox`.
+async fn handle_client(stream: TcpStream, lobby_tx: mpsc::Sender<LobbyEvent>) -> io::Result<()> {
+    // `FramedTcp` keeps the read and write halves of the connection
+    // behind independent locks, so `read_loop` and `write_loop` below
+    // can each drive their own direction of the socket concurrently
+    // through the same `&framed` reference.
+    let framed = FramedTcp::new(stream);
+
+    let name = match recv_msg(&framed).await? {
+        Message::Hello { name } => name,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected a Hello message",
+            ))
+        }
+    };
+
+    let (outbox_tx, mut outbox_rx) = mpsc::channel::<Message>(32);
+    let _ = lobby_tx
+        .send(LobbyEvent::Join {
+            name: name.clone(),
+            outbox: outbox_tx,
+        })
+        .await;
+
+    let read_loop = async {
+        loop {
+            match recv_msg(&framed).await {
+                Ok(Message::Act(action)) => {
+                    let _ = lobby_tx
+                        .send(LobbyEvent::Act {
+                            name: name.clone(),
+                            action,
+                        })
+                        .await;
+                }
+                Ok(Message::Leave { .. }) | Err(_) => {
+                    let _ = lobby_tx
+                        .send(LobbyEvent::Leave { name: name.clone() })
+                        .await;
+                    break;
+                }
+                Ok(Message::Hello { .. }) | Ok(Message::Notice(_)) => {
+                    // A second Hello, or a Notice (which only the
+                    // server ever sends, never a client), is
+                    // unexpected here; ignore it rather than tearing
+                    // down the connection.
+                }
+            }
+        }
+        Ok::<(), io::Error>(())
+    };
+
+    let write_loop = async {
+        while let Some(msg) = outbox_rx.recv().await {
+            send_msg(&framed, msg).await?;
+        }
+        Ok::<(), io::Error>(())
+    };
+
+    futures::try_join!(read_loop, write_loop)?;
+    Ok(())
+}
+
+/// Owns the set of connected players and pairs up waiting ones.
+///
+/// There is only ever one waiting player at a time: as soon as a
+/// second one joins, both are told the other's name via a
+/// `Message::Hello` and the match is recorded in `matches` so later
+/// `Message::Act`s can be relayed between the two. A player who is
+/// waiting with no opponent yet, or who is bounced back to waiting
+/// because their opponent left, gets a `Message::Notice` explaining
+/// why.
+async fn run_lobby(mut events: mpsc::Receiver<LobbyEvent>) {
+    let mut players: HashMap<String, PlayerHandle> = HashMap::new();
+    let mut waiting: Option<String> = None;
+    let mut matches: HashMap<String, String> = HashMap::new();
+
+    while let Some(event) = events.recv().await {
+        match event {
+            LobbyEvent::Join { name, outbox } => {
+                println!("{name} joined the lobby.");
+                players.insert(name.clone(), PlayerHandle { outbox });
+
+                match waiting.take() {
+                    Some(opponent) => {
+                        matches.insert(name.clone(), opponent.clone());
+                        matches.insert(opponent.clone(), name.clone());
+
+                        println!("Matched {name} against {opponent}.");
+                        notify(&players, &opponent, Message::Hello { name: name.clone() }).await;
+                        notify(&players, &name, Message::Hello { name: opponent }).await;
+                    }
+                    None => {
+                        notify(
+                            &players,
+                            &name,
+                            Message::Notice("Waiting for an opponent...".to_string()),
+                        )
+                        .await;
+                        waiting = Some(name);
+                    }
+                }
+            }
+            LobbyEvent::Act { name, action } => {
+                if let Some(opponent) = matches.get(&name).cloned() {
+                    notify(&players, &opponent, Message::Act(action)).await;
+                }
+            }
+            LobbyEvent::Leave { name } => {
+                println!("{name} left the lobby.");
+                players.remove(&name);
+
+                if waiting.as_deref() == Some(name.as_str()) {
+                    waiting = None;
+                }
+
+                if let Some(opponent) = matches.remove(&name) {
+                    matches.remove(&opponent);
+                    notify(&players, &opponent, Message::Leave { name: name.clone() }).await;
+
+                    // The opponent is still connected but no longer
+                    // matched. Pair them with whoever is already
+                    // waiting, or make them the new waiting player.
+                    match waiting.take() {
+                        Some(next) => {
+                            matches.insert(opponent.clone(), next.clone());
+                            matches.insert(next.clone(), opponent.clone());
+
+                            println!("Matched {opponent} against {next}.");
+                            notify(
+                                &players,
+                                &next,
+                                Message::Hello {
+                                    name: opponent.clone(),
+                                },
+                            )
+                            .await;
+                            notify(&players, &opponent, Message::Hello { name: next }).await;
+                        }
+                        None => {
+                            notify(
+                                &players,
+                                &opponent,
+                                Message::Notice(format!(
+                                    "{name} left. Waiting for a new opponent..."
+                                )),
+                            )
+                            .await;
+                            waiting = Some(opponent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Forwards `msg` to `name`'s `handle_client` task, if it is still
+/// connected.
+async fn notify(players: &HashMap<String, PlayerHandle>, name: &str, msg: Message) {
+    if let Some(player) = players.get(name) {
+        let _ = player.outbox.send(msg).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    /// Connects to the lobby at `addr` and says hello as `name`,
+    /// returning the framed connection for the test to drive further.
+    async fn join_lobby(addr: SocketAddr, name: &str) -> FramedTcp {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let framed = FramedTcp::new(stream);
+        send_msg(
+            &framed,
+            Message::Hello {
+                name: name.to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        framed
+    }
+
+    #[tokio::test]
+    async fn two_players_get_matched_and_moves_are_relayed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (lobby_tx, lobby_rx) = mpsc::channel(32);
+        tokio::spawn(run_lobby(lobby_rx));
+        tokio::spawn(async move {
+            loop {
+                let (stream, _peer_addr) = listener.accept().await.unwrap();
+                let lobby_tx = lobby_tx.clone();
+                tokio::spawn(async move {
+                    let _ = handle_client(stream, lobby_tx).await;
+                });
+            }
+        });
+
+        let alice = join_lobby(addr, "alice").await;
+
+        // Alice is first in, so she's told she's waiting before Bob
+        // ever shows up.
+        match recv_msg(&alice).await.unwrap() {
+            Message::Notice(_) => {}
+            other => panic!("expected a waiting Notice, got {other:?}"),
+        }
+
+        let bob = join_lobby(addr, "bob").await;
+
+        match recv_msg(&alice).await.unwrap() {
+            Message::Hello { name } => assert_eq!(name, "bob"),
+            other => panic!("expected Hello(bob), got {other:?}"),
+        }
+        match recv_msg(&bob).await.unwrap() {
+            Message::Hello { name } => assert_eq!(name, "alice"),
+            other => panic!("expected Hello(alice), got {other:?}"),
+        }
+
+        send_msg(&alice, Message::Act(Action::Rock)).await.unwrap();
+        match recv_msg(&bob).await.unwrap() {
+            Message::Act(Action::Rock) => {}
+            other => panic!("expected a relayed Rock, got {other:?}"),
+        }
+    }
+}
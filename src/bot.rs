@@ -0,0 +1,123 @@
+//! Drives a scripted/AI opponent through a child process, instead of
+//! reading its moves off a socket.
+//!
+//! The crate writes the round state as one line of JSON to the
+//! child's stdin, and reads back a single `r`/`p`/`s` character from
+//! its stdout as the bot's move.
+
+use rock_paper_scissor::message::Action;
+use serde::Serialize;
+use std::io;
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdout, Command};
+
+/// The state handed to the bot before each round.
+///
+/// Field names are from the bot's own point of view: what it sees as
+/// `opponent_prior_moves` is the human player's move history, tracked
+/// as `my_prior_moves` on our side in `turn` below.
+#[derive(Debug, Serialize)]
+struct GameState {
+    round: u32,
+    opponent_prior_moves: Vec<Action>,
+}
+
+/// A scripted opponent spawned as a child process, piping the round
+/// state to its stdin and reading its move back from its stdout.
+pub struct Bot {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Bot {
+    /// Spawns `command` through a shell, with its stdio piped so we
+    /// can talk to it.
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Drain the child's stderr for as long as the process lives.
+        // Without this, a chatty bot that fills its stderr pipe
+        // buffer would block on writing to it, and we'd deadlock
+        // waiting on its stdout in `turn` below.
+        let stderr = child.stderr.take().expect("stderr was piped");
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                eprintln!("[bot] {line}");
+            }
+        });
+
+        let stdout = BufReader::new(child.stdout.take().expect("stdout was piped"));
+
+        Ok(Self { child, stdout })
+    }
+
+    /// Sends the round state to the bot and reads back its move.
+    ///
+    /// Returns `Ok(None)` if the bot process has already exited or
+    /// exits instead of answering, which ends the match the same way
+    /// a human quitting does.
+    pub async fn turn(
+        &mut self,
+        round: u32,
+        my_prior_moves: &[Action],
+    ) -> io::Result<Option<Action>> {
+        if let Some(status) = self.child.try_wait()? {
+            println!("The bot already exited with status {status}.");
+            return Ok(None);
+        }
+
+        let state = GameState {
+            round,
+            opponent_prior_moves: my_prior_moves.to_vec(),
+        };
+        let mut line = serde_json::to_string(&state).unwrap();
+        line.push('\n');
+
+        let stdin = self.child.stdin.as_mut().expect("stdin was piped");
+        if let Err(err) = stdin.write_all(line.as_bytes()).await {
+            // The bot may have exited between the `try_wait` above and
+            // this write, in which case the pipe is already broken.
+            // Treat that the same as it closing its stdout below,
+            // rather than propagating a confusing I/O error.
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                return Err(err);
+            }
+            let status = self.child.wait().await?;
+            println!("The bot exited with status {status} instead of answering.");
+            return Ok(None);
+        }
+
+        let mut answer = String::new();
+        let bytes_read = self.stdout.read_line(&mut answer).await?;
+
+        if bytes_read == 0 {
+            // The bot closed its stdout, most likely because it
+            // exited. `wait` reaps it so we can report its status.
+            let status = self.child.wait().await?;
+            println!("The bot exited with status {status} instead of answering.");
+            return Ok(None);
+        }
+
+        let action = match answer.trim() {
+            "r" => Action::Rock,
+            "p" => Action::Paper,
+            "s" => Action::Scissor,
+            other => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("bot produced an unrecognized move: {other:?}"),
+                ))
+            }
+        };
+
+        Ok(Some(action))
+    }
+}
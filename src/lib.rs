@@ -0,0 +1,10 @@
+//! Shared types and helpers used by both the two-player client
+//! (`src/main.rs`) and the lobby server (`src/bin/server.rs`).
+//!
+//! Splitting these out into a library means the server can reuse the
+//! exact same `Message` wire format and `Transport` framing as the
+//! client instead of duplicating them.
+
+pub mod message;
+pub mod transport;
+pub mod utils;
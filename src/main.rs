@@ -81,24 +81,28 @@
 //! join!(stdin_task, socket_task);
 //! ```
 
-// Declare modules. Each module corresponds to a file. For example,
-// `mod message` is for the `message.rs` file.
-mod message;
-mod utils;
-
-// Imports the types and functions we want to use.
-use crate::message::Action;
-use crate::utils::recv_msg;
+// `bot` is specific to this client binary (the lobby server has no
+// use for a scripted opponent), so it is declared here rather than in
+// the `rock_paper_scissor` library crate.
+mod bot;
+
+// Imports the types and functions we want to use. `message`,
+// `transport` and `utils` now live in the `rock_paper_scissor` library
+// crate (see `src/lib.rs`), so that the lobby server in
+// `src/bin/server.rs` can reuse them too.
+use bot::Bot;
 use clap::Parser;
 use futures::try_join;
-use message::Message;
+use rock_paper_scissor::message::{Action, Message};
+use rock_paper_scissor::transport::{
+    AnyTransport, AnyTransportReceiver, AnyTransportSender, FramedTcp, TransportKind,
+};
+use rock_paper_scissor::utils::{recv_msg, send_msg};
 use std::io;
 use std::net::SocketAddr;
-use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::net::UdpSocket;
-use utils::send_msg;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::net::TcpStream;
 
 // The argument type and return type of a function can help you guess
 // the purpose of the function. Let's see the function for example.
@@ -128,13 +132,47 @@ struct Args {
     /// The name of the player.
     pub name: String,
 
-    /// The IP:port address that the player binds to. For example,
-    /// "127.0.0.1:44444".
-    pub self_addr: SocketAddr,
-
-    /// The IP:port address of the opponent player. For example,
-    /// "127.0.0.1:55555".
-    pub other_addr: SocketAddr,
+    /// Which kind of socket to play over.
+    #[clap(long, value_enum, default_value_t = TransportKind::Udp)]
+    pub transport: TransportKind,
+
+    /// The local endpoint that the player binds to. For `udp`/`tcp`
+    /// this is an "ip:port" address, e.g. "127.0.0.1:44444". For
+    /// `uds` this is a filesystem path for the local socket. With
+    /// `--transport tcp`, only the `--listen` side binds to it; the
+    /// connecting side ignores it.
+    ///
+    /// With `--transport uds` it doesn't matter which player is
+    /// started first: connecting to the opponent's socket file is
+    /// retried for a few seconds in case it hasn't bound yet.
+    pub self_addr: String,
+
+    /// The opponent's endpoint, in the same format as `self_addr`.
+    pub other_addr: String,
+
+    /// For the `tcp` transport, listen for the opponent's connection
+    /// instead of connecting to it. Ignored by the other transports.
+    #[clap(long)]
+    pub listen: bool,
+
+    /// How many seconds a player may take to enter a move before
+    /// forfeiting the round.
+    #[clap(long, default_value_t = 30)]
+    pub turn_timeout: u64,
+
+    /// Play against a scripted/AI opponent instead of a networked
+    /// human. `command` is spawned as a child process and its moves
+    /// are exchanged over its stdio instead of a socket, so
+    /// `self_addr`/`other_addr` are ignored.
+    #[clap(long)]
+    pub bot: Option<String>,
+
+    /// Play through a lobby server (`src/bin/server.rs`) instead of
+    /// connecting directly to a single opponent. `addr` is the
+    /// server's "ip:port" address; `transport`/`self_addr`/
+    /// `other_addr`/`listen` are ignored.
+    #[clap(long)]
+    pub lobby: Option<String>,
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -154,18 +192,37 @@ async fn main() -> io::Result<()> {
     // write `let name = opts.name;`, etc.
     let Args {
         name,
+        transport,
         self_addr,
         other_addr,
+        listen,
+        turn_timeout,
+        bot,
+        lobby,
     } = opts;
+    let turn_timeout = Duration::from_secs(turn_timeout);
+
+    // `--bot` skips the networking entirely and plays against a
+    // scripted opponent over a child process's stdio instead.
+    if let Some(command) = bot {
+        return play_against_bot(&command, turn_timeout).await;
+    }
 
-    // Creates a UDP socket, providing the local and remote addresses.
+    // `--lobby` skips the direct two-peer connection below and
+    // instead dials the lobby server, which pairs us up with whoever
+    // else is waiting.
+    if let Some(addr) = lobby {
+        return play_in_lobby(&addr, name, turn_timeout).await;
+    }
+
+    // Builds whichever kind of socket `--transport` asked for and
+    // wires it up to the opponent.
     //
     // The .await marks the point where a thread can make a pause and
     // "yield" the execution. For example, the socket reading
     // `socket.recv().await` can yield when the data is not avaible,
     // and pauses until the data becomes ready.
-    let sock = UdpSocket::bind(self_addr).await?;
-    sock.connect(other_addr).await?;
+    let transport = AnyTransport::connect(transport, &self_addr, &other_addr, listen).await?;
 
     // Sleep for a while to wait for the oppoent to get ready.
     //
@@ -192,8 +249,8 @@ async fn main() -> io::Result<()> {
     // `async { .. }` block creates a future in-place.  This
     // future evaluates to a Result when it is awaited.
     let say_hello_future = async {
-        let msg = Message::Hello { name };
-        let result: io::Result<()> = send_msg(&sock, msg).await;
+        let msg = Message::Hello { name: name.clone() };
+        let result: io::Result<()> = send_msg(&transport, msg).await;
         result
     };
     let result = say_hello_future.await; // Evaluate/Execute the future
@@ -209,13 +266,13 @@ async fn main() -> io::Result<()> {
     // ```
     // let Message::Hello {
     //     name: opponent_name,
-    // } = recv_msg(&sock).await?
+    // } = recv_msg(&transport).await?
     // else {
     //     panic!("unexpected message type");
     // };
     // ```
     let opponent_name = {
-        let result = recv_msg(&sock).await;
+        let result = recv_msg(&transport).await;
 
         match result {
             Ok(Message::Hello { name }) => name,
@@ -229,22 +286,23 @@ async fn main() -> io::Result<()> {
     // Here creates two async tasks, one scanning user input from the
     // terminal, the other reading data from the socket.
     //
-    // Both tasks share the UDP socket. Let's wrap the socket in the
-    // `Arc` pointer, so that both tasks can get a copy of the pointer
-    // to operate on the same socket.
-    let sock_ptr1 = Arc::new(sock);
-    let sock_ptr2 = sock_ptr1.clone(); // The .clone() copeis the pointer, not the underlying socket.
+    // Neither task needs the other's half of the transport, so we
+    // split it into an owned sending half and an owned receiving
+    // half instead of sharing one `Arc<AnyTransport>` between them:
+    // the types themselves now guarantee that only `my_turn` ever
+    // writes and only `opponents_turn` ever reads.
+    let (sender, receiver) = transport.into_split();
 
     // Now creates to futures. Note that we does not call .await on
     // purpose.
-    let my_turn_future = my_turn(sock_ptr1);
-    let opponents_turn_future = opponents_turn(sock_ptr2);
+    let my_turn_future = my_turn(sender, name, turn_timeout);
+    let opponents_turn_future = opponents_turn(receiver, turn_timeout);
 
     // Let's execute both futures concurrently and returns both
     // outputs when both futures complete. The `try_join!` macro is
     // the sibling of `join!`. It similar to `join!` but checks if any
     // one of future evaluates to `Err()`.
-    let (my_action_option, oppo_action) = try_join!(my_turn_future, opponents_turn_future)?;
+    let (my_action_option, oppo_action_option) = try_join!(my_turn_future, opponents_turn_future)?;
 
     // Check if the user provides a move or quits by unpacking the
     // `Option`. There are two more equivalent ways to write the code.
@@ -268,6 +326,16 @@ async fn main() -> io::Result<()> {
         return Ok(());
     };
 
+    // Same idea for the opponent's move: `None` here means the
+    // opponent's turn timed out or they sent `Message::Leave`, either
+    // of which forfeits the round in our favor.
+    let oppo_action = if let Some(act) = oppo_action_option {
+        act
+    } else {
+        println!("The opponent abandoned the round. You win!");
+        return Ok(());
+    };
+
     // Print the moves of both sides.
     //
     // Here creates a `|args| { ... }` closure to that will be called
@@ -302,98 +370,313 @@ async fn main() -> io::Result<()> {
 ///
 /// This function comes in three outcomes:
 /// - `Ok(Some(action))` - The user gives an action.
-/// - `Ok(None)` - The user quits during the process.
+/// - `Ok(None)` - The user quits, or takes longer than `turn_timeout`
+///   to answer and forfeits the round.
 /// - `Err(err)` - An I/O error occurred.
-async fn my_turn(sock: Arc<UdpSocket>) -> io::Result<Option<Action>> {
-    // Create a Stdin object from tokio library.  We use tokio's
-    // Stdin instead of standard library's because it supports
-    // .await syntax.
+async fn my_turn(
+    mut sender: AnyTransportSender,
+    name: String,
+    turn_timeout: Duration,
+) -> io::Result<Option<Action>> {
     let stdin = tokio::io::stdin();
+    let mut lines = BufReader::new(stdin).lines();
+
+    let action = match read_my_move(&mut lines, turn_timeout).await? {
+        Some(action) => action,
+        None => {
+            // The player quit or took too long to answer. Either way,
+            // forfeit the round by telling the opponent we're
+            // leaving.
+            sender.send(Message::Leave { name }).await?;
+            return Ok(None);
+        }
+    };
 
-    // Wrap the stdin in tokio's BufReader to enable reading
-    // line-by-line.
-    let reader = BufReader::new(stdin);
+    // Send a message to the opponent.
+    let msg = Message::Act(action);
+    sender.send(msg).await?;
 
-    // Convert the reader to a stream of lines.
-    let mut lines = reader.lines();
+    // The last `Ok` is necessary because the function expects a
+    // `Result<_>` return value.
+    Ok(Some(action))
+}
 
+/// Reads one validated move from stdin, giving the player up to
+/// `turn_timeout` to answer.
+///
+/// Returns `Ok(None)` if the player quits (`q` or end-of-file) or
+/// takes longer than `turn_timeout` to answer.
+async fn read_my_move(
+    lines: &mut Lines<BufReader<Stdin>>,
+    turn_timeout: Duration,
+) -> io::Result<Option<Action>> {
     // The loop repeats until a valid command is read from the user.
     // That is, whenever a valid command is recognized, it immediately
-    // break the loop.
-    let action: Action = loop {
-        println!("Enter your move and press enter.");
-        println!("- r: Rock");
-        println!("- p: Paper");
-        println!("- s: Scissor");
-        println!("- q: Quit");
-
-        // Wait for the next line. It returns a result.
-        let result: Result<_, _> = lines.next_line().await;
-
-        // Unpack the result. It gets an Option<String>. The `?`
-        // syntax unpacks a `Result` variable. It unpacks the inner
-        // value if the variable is `Ok`. Otherwise, it returns an
-        // error from the function. The syntax is valid only when the
-        // return type of current function is also `Result`.
-        let opt: Option<String> = result?;
-
-        // Unpack the opt Option<String>.
-        //
-        // If it is Some(line), get the inner value. Otherwise, it
-        // reaches the end of file so we return early.
-        let line: String = match opt {
-            Some(line) => line,
-            None => return Ok(None),
-        };
+    // breaks the loop. The whole loop is wrapped in `timeout` so a
+    // player who never answers doesn't stall their opponent forever.
+    let read_move = async {
+        loop {
+            println!("Enter your move and press enter.");
+            println!("- r: Rock");
+            println!("- p: Paper");
+            println!("- s: Scissor");
+            println!("- q: Quit");
+
+            // Wait for the next line. It returns a result.
+            let result: Result<_, _> = lines.next_line().await;
+
+            // Unpack the result. It gets an Option<String>. The `?`
+            // syntax unpacks a `Result` variable. It unpacks the inner
+            // value if the variable is `Ok`. Otherwise, it returns an
+            // error from the function. The syntax is valid only when the
+            // return type of current function is also `Result`.
+            let opt: Option<String> = result?;
+
+            // Unpack the opt Option<String>.
+            //
+            // If it is Some(line), get the inner value. Otherwise, it
+            // reaches the end of file so we return early.
+            let line: String = match opt {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+
+            // Parse the input line.
+            let action: Action = match line.as_str() {
+                "p" => Action::Paper,
+                "s" => Action::Scissor,
+                "r" => Action::Rock,
+                "q" => {
+                    // User requests quit. Let's return early.
+                    return Ok(None);
+                }
+                _ => {
+                    // In this hand, user gives a command not understood
+                    // by us. Re-run the loop to get the next line.
+                    println!("Command not understood");
+                    continue;
+                }
+            };
+
+            // Exit the loop.
+            break Ok(Some(action));
+        }
+    };
+
+    match tokio::time::timeout(turn_timeout, read_move).await {
+        Ok(result) => result,
+        Err(_elapsed) => {
+            println!("You took too long.");
+            Ok(None)
+        }
+    }
+}
 
-        // The code above can be shortened to the following. We wrote
-        // the verbose version for clarity.
-        //
-        // ```
-        // let Some(line) = lines.next_line().await.unwrap() else {
-        //     break;
-        // };
-        // ```
-
-        // Parse the input line.
-        let action: Action = match line.as_str() {
-            "p" => Action::Paper,
-            "s" => Action::Scissor,
-            "r" => Action::Rock,
-            "q" => {
-                // User requests quit. Let's return early.
-                return Ok(None);
+/// Gets the opponent's move by reading the socket.
+///
+/// Returns `Ok(None)` if the opponent's turn times out or they send
+/// `Message::Leave`, either of which forfeits the round in our favor.
+async fn opponents_turn(
+    mut receiver: AnyTransportReceiver,
+    turn_timeout: Duration,
+) -> io::Result<Option<Action>> {
+    // Wait for the opponent's move, but give up after `turn_timeout`
+    // so a silent opponent can't stall the game forever.
+    let wait_for_move = async {
+        loop {
+            match receiver.recv().await? {
+                Message::Act(action) => return Ok(Some(action)),
+                Message::Leave { name } => {
+                    println!("{name} left the game.");
+                    return Ok(None);
+                }
+                // A lobby server (`src/bin/server.rs`) may send a
+                // `Notice`, or re-send `Hello` for a later match;
+                // neither affects this round, so keep waiting for
+                // the actual move.
+                Message::Notice(_) | Message::Hello { .. } => continue,
             }
-            _ => {
-                // In this hand, user gives a command not understood
-                // by us. Re-run the loop to get the next line.
-                println!("Command not understood");
-                continue;
+        }
+    };
+
+    match tokio::time::timeout(turn_timeout, wait_for_move).await {
+        Ok(result) => result,
+        Err(_elapsed) => {
+            println!("The opponent took too long to answer.");
+            Ok(None)
+        }
+    }
+}
+
+/// Plays round after round against a scripted opponent spawned from
+/// `command`, instead of a networked human.
+///
+/// Unlike the two-process game above, which plays exactly one round,
+/// this keeps going so the bot actually gets a `my_prior_moves`
+/// history to react to. It stops as soon as either side gives up:
+/// the human by quitting or timing out, the bot by exiting.
+async fn play_against_bot(command: &str, turn_timeout: Duration) -> io::Result<()> {
+    let mut bot = Bot::spawn(command)?;
+
+    let stdin = tokio::io::stdin();
+    let mut lines = BufReader::new(stdin).lines();
+
+    let get_action_name = |action: Action| match action {
+        Action::Rock => "rock",
+        Action::Paper => "paper",
+        Action::Scissor => "scissor",
+    };
+
+    let mut my_prior_moves = Vec::new();
+    let mut round: u32 = 1;
+
+    loop {
+        let my_action = match read_my_move(&mut lines, turn_timeout).await? {
+            Some(action) => action,
+            None => {
+                println!("You quit. Loser!");
+                return Ok(());
             }
         };
 
-        // Exit the loop.
-        break action;
-    };
+        let bot_action = match bot.turn(round, &my_prior_moves).await? {
+            Some(action) => action,
+            None => {
+                println!("The bot gave up. You win!");
+                return Ok(());
+            }
+        };
 
-    // Send a message to the opponent.
-    let msg = Message::Act(action);
-    send_msg(&sock, msg).await?;
+        my_prior_moves.push(my_action);
 
-    // The last `Ok` is necessary because the function expects a
-    // `Result<_>` return value.
-    Ok(Some(action))
+        println!("Round {round}:");
+        println!("You play {}.", get_action_name(my_action));
+        println!("The bot plays {}.", get_action_name(bot_action));
+
+        match (my_action, bot_action) {
+            (Action::Rock, Action::Rock)
+            | (Action::Paper, Action::Paper)
+            | (Action::Scissor, Action::Scissor) => println!("Fair."),
+
+            (Action::Rock, Action::Scissor)
+            | (Action::Paper, Action::Rock)
+            | (Action::Scissor, Action::Paper) => println!("You win!"),
+
+            (Action::Rock, Action::Paper)
+            | (Action::Paper, Action::Scissor)
+            | (Action::Scissor, Action::Rock) => println!("You lose!"),
+        }
+
+        round += 1;
+    }
 }
 
-/// Gets the opponent's move by reading the socket.
-async fn opponents_turn(sock: Arc<UdpSocket>) -> io::Result<Action> {
-    // Receive a message from the opponent
-    let msg = recv_msg(&sock).await?;
+/// Plays one round against whoever the lobby server at `addr` pairs
+/// us up with, instead of connecting directly to a single opponent.
+///
+/// Unlike the direct two-peer flow in `main`, the lobby may keep us
+/// waiting -- and tell us so with `Message::Notice` -- before the
+/// `Message::Hello` that actually announces an opponent arrives.
+async fn play_in_lobby(addr: &str, name: String, turn_timeout: Duration) -> io::Result<()> {
+    let self_addr: SocketAddr = addr
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let stream = TcpStream::connect(self_addr).await?;
+    let transport = FramedTcp::new(stream);
+
+    send_msg(&transport, Message::Hello { name: name.clone() }).await?;
+
+    let opponent_name = loop {
+        match recv_msg(&transport).await? {
+            Message::Hello { name } => break name,
+            Message::Notice(text) => println!("{text}"),
+            msg => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected message while waiting in the lobby: {msg:?}"),
+                ))
+            }
+        }
+    };
+
+    println!("{opponent_name} enters the game!");
+
+    let (sender, receiver) = transport.into_split();
+    let my_turn_future = my_turn(sender, name, turn_timeout);
+    let opponents_turn_future = opponents_turn(receiver, turn_timeout);
+    let (my_action_option, oppo_action_option) = try_join!(my_turn_future, opponents_turn_future)?;
+
+    let my_action = if let Some(act) = my_action_option {
+        act
+    } else {
+        println!("You quits. Loser!");
+        return Ok(());
+    };
+
+    let oppo_action = if let Some(act) = oppo_action_option {
+        act
+    } else {
+        println!("The opponent abandoned the round. You win!");
+        return Ok(());
+    };
 
-    // Unpack a message.
-    let Message::Act(action) = msg else {
-        panic!("Unexpected message type");
+    let get_action_name = |action: Action| match action {
+        Action::Rock => "rock",
+        Action::Paper => "paper",
+        Action::Scissor => "scissor",
     };
+    println!("You plays {}.", get_action_name(my_action));
+    println!("The opponent plays {}.", get_action_name(oppo_action));
+
+    match (my_action, oppo_action) {
+        (Action::Rock, Action::Rock)
+        | (Action::Paper, Action::Paper)
+        | (Action::Scissor, Action::Scissor) => println!("Fair."),
 
-    Ok(action)
+        (Action::Rock, Action::Scissor)
+        | (Action::Paper, Action::Rock)
+        | (Action::Scissor, Action::Paper) => println!("You win!"),
+
+        (Action::Rock, Action::Paper)
+        | (Action::Paper, Action::Scissor)
+        | (Action::Scissor, Action::Rock) => println!("You lose!"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn opponents_turn_forfeits_after_timeout() {
+        let dir = std::env::temp_dir();
+        let self_path = dir.join(format!("rps-test-{}-self.sock", std::process::id()));
+        let other_path = dir.join(format!("rps-test-{}-other.sock", std::process::id()));
+        let _ = std::fs::remove_file(&self_path);
+        let _ = std::fs::remove_file(&other_path);
+
+        // Bind the "opponent's" socket but never send anything over
+        // it, so `opponents_turn` has nothing to read and must give
+        // up once `turn_timeout` elapses.
+        let _other = tokio::net::UnixDatagram::bind(&other_path).unwrap();
+        let transport = AnyTransport::connect(
+            TransportKind::Uds,
+            self_path.to_str().unwrap(),
+            other_path.to_str().unwrap(),
+            false,
+        )
+        .await
+        .unwrap();
+        let (_sender, receiver) = transport.into_split();
+
+        let result = opponents_turn(receiver, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert!(result.is_none());
+
+        let _ = std::fs::remove_file(&self_path);
+        let _ = std::fs::remove_file(&other_path);
+    }
 }
@@ -3,9 +3,17 @@ use serde::{Deserialize, Serialize};
 /// The message that is exchanged between the players.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Message {
-    Hello { name: String },
-    Leave { name: String },
+    Hello {
+        name: String,
+    },
+    Leave {
+        name: String,
+    },
     Act(Action),
+    /// A lobby status update, sent by `src/bin/server.rs` to a player
+    /// waiting for an opponent. Never sent by the two-peer client in
+    /// `src/main.rs`.
+    Notice(String),
 }
 
 /// Defines the action made by the player.
@@ -0,0 +1,358 @@
+use crate::message::Message;
+use crate::utils::MessageCodec;
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::{SinkExt, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket, UnixDatagram};
+use tokio::sync::Mutex;
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+use tokio_util::udp::UdpFramed;
+
+/// Abstracts over the different kinds of sockets the game can be
+/// played over, so the framing and game-loop code only need to know
+/// how to send and receive a [`Message`], not which transport is
+/// carrying it underneath.
+#[async_trait]
+pub trait Transport {
+    /// Sends one message over the transport.
+    async fn send(&self, msg: Message) -> io::Result<()>;
+
+    /// Receives one message from the transport.
+    async fn recv(&self) -> io::Result<Message>;
+}
+
+#[async_trait]
+impl Transport for UdpSocket {
+    async fn send(&self, msg: Message) -> io::Result<()> {
+        let addr = self.peer_addr()?;
+        let mut framed = UdpFramed::new(self, MessageCodec);
+        framed.send((msg, addr)).await
+    }
+
+    async fn recv(&self) -> io::Result<Message> {
+        let mut framed = UdpFramed::new(self, MessageCodec);
+
+        match framed.next().await {
+            Some(Ok((msg, _addr))) => Ok(msg),
+            Some(Err(err)) => Err(err),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The socket is closed",
+            )),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for UnixDatagram {
+    async fn send(&self, msg: Message) -> io::Result<()> {
+        let mut buf = BytesMut::new();
+        MessageCodec.encode(msg, &mut buf)?;
+        UnixDatagram::send(self, &buf).await?;
+        Ok(())
+    }
+
+    async fn recv(&self) -> io::Result<Message> {
+        // Unlike a TCP stream, one `recv` call on a datagram socket
+        // always returns exactly one datagram, so the whole frame is
+        // guaranteed to arrive in a single read.
+        let mut raw = vec![0u8; 64 * 1024];
+        let len = UnixDatagram::recv(self, &mut raw).await?;
+        let mut buf = BytesMut::from(&raw[..len]);
+
+        match MessageCodec.decode(&mut buf)? {
+            Some(msg) => Ok(msg),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The datagram did not contain a whole message",
+            )),
+        }
+    }
+}
+
+/// A TCP connection framed with [`MessageCodec`], usable from `&self`.
+///
+/// Unlike `UdpSocket`/`UnixDatagram`, a plain `&TcpStream` implements
+/// neither `AsyncRead` nor `AsyncWrite` -- only the owned stream, or
+/// the owned halves returned by `into_split`, do. So this wraps one
+/// owned half per direction instead, each behind its own lock: a
+/// *separate* lock per direction (rather than one shared lock around
+/// a single `Framed`) so a `recv` that's still waiting on the
+/// opponent's next message can never stall a concurrent `send`.
+/// Keeping the halves here, instead of rebuilding a `Framed` on every
+/// call, also means a `recv` never throws away a second frame that
+/// arrived in the same read as the first.
+pub struct FramedTcp {
+    reader: Mutex<FramedRead<OwnedReadHalf, MessageCodec>>,
+    writer: Mutex<FramedWrite<OwnedWriteHalf, MessageCodec>>,
+}
+
+impl FramedTcp {
+    pub fn new(stream: TcpStream) -> Self {
+        let (read_half, write_half) = stream.into_split();
+        Self {
+            reader: Mutex::new(FramedRead::new(read_half, MessageCodec)),
+            writer: Mutex::new(FramedWrite::new(write_half, MessageCodec)),
+        }
+    }
+
+    /// Tears the connection back down into its persistent read/write
+    /// halves, for callers (like [`AnyTransport::into_split`]) that
+    /// want to hand the two directions to separate owning tasks
+    /// instead of sharing them behind `&self` and a lock.
+    fn into_parts(
+        self,
+    ) -> (
+        FramedRead<OwnedReadHalf, MessageCodec>,
+        FramedWrite<OwnedWriteHalf, MessageCodec>,
+    ) {
+        (self.reader.into_inner(), self.writer.into_inner())
+    }
+}
+
+#[async_trait]
+impl Transport for FramedTcp {
+    async fn send(&self, msg: Message) -> io::Result<()> {
+        self.writer.lock().await.send(msg).await
+    }
+
+    async fn recv(&self) -> io::Result<Message> {
+        match self.reader.lock().await.next().await {
+            Some(result) => result,
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "The socket is closed",
+            )),
+        }
+    }
+}
+
+/// Which kind of socket to play the game over. Selected on the
+/// command line with `--transport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TransportKind {
+    /// Play over a connected UDP socket (the original transport).
+    Udp,
+    /// Play over a pair of Unix datagram sockets, addressed by
+    /// filesystem path instead of `SocketAddr`.
+    Uds,
+    /// Play over a TCP stream. One side must `--listen` while the
+    /// other connects.
+    Tcp,
+}
+
+/// A socket of one of the kinds named by [`TransportKind`], hiding
+/// which one behind the [`Transport`] trait.
+pub enum AnyTransport {
+    Udp(UdpSocket),
+    Uds(UnixDatagram),
+    Tcp(FramedTcp),
+}
+
+#[async_trait]
+impl Transport for AnyTransport {
+    async fn send(&self, msg: Message) -> io::Result<()> {
+        match self {
+            AnyTransport::Udp(sock) => sock.send(msg).await,
+            AnyTransport::Uds(sock) => sock.send(msg).await,
+            AnyTransport::Tcp(sock) => sock.send(msg).await,
+        }
+    }
+
+    async fn recv(&self) -> io::Result<Message> {
+        match self {
+            AnyTransport::Udp(sock) => sock.recv().await,
+            AnyTransport::Uds(sock) => sock.recv().await,
+            AnyTransport::Tcp(sock) => sock.recv().await,
+        }
+    }
+}
+
+impl AnyTransport {
+    /// Builds the local socket named by `self_addr` for the given
+    /// `kind`, and wires it up to the opponent at `other_addr`.
+    ///
+    /// For `udp`/`tcp`, the addresses are "ip:port" socket addresses.
+    /// For `uds`, they are filesystem paths for Unix datagram
+    /// sockets. `listen` only matters for `tcp`, where one side must
+    /// listen for the opponent's connection while the other connects.
+    pub async fn connect(
+        kind: TransportKind,
+        self_addr: &str,
+        other_addr: &str,
+        listen: bool,
+    ) -> io::Result<Self> {
+        match kind {
+            TransportKind::Udp => {
+                let self_addr: SocketAddr = self_addr.parse().map_err(invalid_addr)?;
+                let other_addr: SocketAddr = other_addr.parse().map_err(invalid_addr)?;
+
+                let sock = UdpSocket::bind(self_addr).await?;
+                sock.connect(other_addr).await?;
+                Ok(AnyTransport::Udp(sock))
+            }
+            TransportKind::Uds => {
+                // Binding to a path that is already in use as a
+                // socket fails, so clear out a stale socket file left
+                // behind by a previous run before binding our own.
+                let _ = std::fs::remove_file(self_addr);
+
+                let sock = UnixDatagram::bind(self_addr)?;
+
+                // Unlike UDP's `connect`, which just records an
+                // address, a Unix *datagram* `connect` requires the
+                // peer's socket file to already exist. Whichever
+                // player starts first would otherwise fail with
+                // `ENOENT` before the other side has had a chance to
+                // bind, so retry for a few seconds instead of
+                // requiring a particular start order.
+                connect_uds_with_retry(&sock, other_addr).await?;
+                Ok(AnyTransport::Uds(sock))
+            }
+            TransportKind::Tcp => {
+                let stream = if listen {
+                    let self_addr: SocketAddr = self_addr.parse().map_err(invalid_addr)?;
+                    let listener = TcpListener::bind(self_addr).await?;
+                    let (stream, _peer_addr) = listener.accept().await?;
+                    stream
+                } else {
+                    let other_addr: SocketAddr = other_addr.parse().map_err(invalid_addr)?;
+                    TcpStream::connect(other_addr).await?
+                };
+                Ok(AnyTransport::Tcp(FramedTcp::new(stream)))
+            }
+        }
+    }
+}
+
+/// Turns a `SocketAddr` parse error into an `io::Error` so it can be
+/// propagated with `?` alongside the genuine I/O errors above.
+fn invalid_addr(err: std::net::AddrParseError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, err)
+}
+
+/// Connects `sock` to the Unix datagram socket at `path`, retrying for
+/// a few seconds if it doesn't exist yet.
+///
+/// `connect(2)` on a datagram socket fails with `ENOENT` until the
+/// peer has actually created its socket file by binding, so the very
+/// first player to start would otherwise always lose this race.
+/// Retrying here means either side can be started first.
+async fn connect_uds_with_retry(sock: &UnixDatagram, path: &str) -> io::Result<()> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+
+    loop {
+        match sock.connect(path) {
+            Ok(()) => return Ok(()),
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound
+                    && tokio::time::Instant::now() < deadline =>
+            {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+impl AnyTransport {
+    /// Splits the transport into an owned sending half and an owned
+    /// receiving half, so the task that writes moves and the task
+    /// that reads them can each hold a distinct value instead of both
+    /// sharing an `Arc<AnyTransport>`.
+    ///
+    /// A `TcpStream` already has its own owned-half split
+    /// (`into_split`), which we reuse here. UDP and Unix datagram
+    /// sockets have no such split built in -- a single socket both
+    /// sends and receives -- so each half instead holds its own `Arc`
+    /// around the shared socket. That `Arc` is private to this
+    /// module: the two halves still only expose sending or receiving,
+    /// never both, so callers can't accidentally read and write from
+    /// the same place the way a shared `Arc<UdpSocket>` allows.
+    pub fn into_split(self) -> (AnyTransportSender, AnyTransportReceiver) {
+        match self {
+            AnyTransport::Udp(sock) => {
+                let sock = Arc::new(sock);
+                (
+                    AnyTransportSender::Udp(sock.clone()),
+                    AnyTransportReceiver::Udp(sock),
+                )
+            }
+            AnyTransport::Uds(sock) => {
+                let sock = Arc::new(sock);
+                (
+                    AnyTransportSender::Uds(sock.clone()),
+                    AnyTransportReceiver::Uds(sock),
+                )
+            }
+            AnyTransport::Tcp(framed) => {
+                let (reader, writer) = framed.into_parts();
+                (
+                    AnyTransportSender::Tcp(writer),
+                    AnyTransportReceiver::Tcp(reader),
+                )
+            }
+        }
+    }
+}
+
+/// The sending half of a split [`AnyTransport`], returned by
+/// [`AnyTransport::into_split`].
+pub enum AnyTransportSender {
+    Udp(Arc<UdpSocket>),
+    Uds(Arc<UnixDatagram>),
+    Tcp(FramedWrite<OwnedWriteHalf, MessageCodec>),
+}
+
+impl AnyTransportSender {
+    /// Sends one message over the transport.
+    ///
+    /// This takes `&mut self` rather than `&self`: a plain `&`
+    /// reference to the owned `OwnedWriteHalf` behind the `Tcp`
+    /// variant doesn't implement `AsyncWrite` (only the owned half,
+    /// or a `&mut` to it, does), so driving the persistent
+    /// `FramedWrite` needs a mutable borrow.
+    pub async fn send(&mut self, msg: Message) -> io::Result<()> {
+        match self {
+            AnyTransportSender::Udp(sock) => Transport::send(&**sock, msg).await,
+            AnyTransportSender::Uds(sock) => Transport::send(&**sock, msg).await,
+            AnyTransportSender::Tcp(framed) => framed.send(msg).await,
+        }
+    }
+}
+
+/// The receiving half of a split [`AnyTransport`], returned by
+/// [`AnyTransport::into_split`].
+pub enum AnyTransportReceiver {
+    Udp(Arc<UdpSocket>),
+    Uds(Arc<UnixDatagram>),
+    Tcp(FramedRead<OwnedReadHalf, MessageCodec>),
+}
+
+impl AnyTransportReceiver {
+    /// Receives one message from the transport.
+    ///
+    /// The `Tcp` variant holds onto its `FramedRead` across calls
+    /// instead of rebuilding one each time: a single `read` syscall
+    /// can land more than one frame in the decoder's buffer, and
+    /// rebuilding the `FramedRead` would silently drop whatever was
+    /// buffered past the frame just returned.
+    pub async fn recv(&mut self) -> io::Result<Message> {
+        match self {
+            AnyTransportReceiver::Udp(sock) => Transport::recv(&**sock).await,
+            AnyTransportReceiver::Uds(sock) => Transport::recv(&**sock).await,
+            AnyTransportReceiver::Tcp(framed) => match framed.next().await {
+                Some(result) => result,
+                None => Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "The socket is closed",
+                )),
+            },
+        }
+    }
+}
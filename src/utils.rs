@@ -1,116 +1,145 @@
 use crate::message::Message;
+use crate::transport::Transport;
+use bytes::{Buf, BufMut, BytesMut};
 use std::io;
-use tokio::net::UdpSocket; // We use the socket type from tokio, not std's.
+use tokio_util::codec::{Decoder, Encoder};
 
-pub async fn send_msg(sock: &UdpSocket, msg: Message) -> io::Result<()> {
-    // Serialize the message to a JSON string.
-    let json_text: String = serde_json::to_string(&msg).unwrap();
+/// Encodes and decodes [`Message`] values on the wire.
+///
+/// It keeps the same framing the hand-rolled `send_exact`/`recv_exact`
+/// loops used to build by hand: a 4-byte little-endian length header
+/// followed by the JSON encoding of the message.
+///
+/// offset | 0..4        | 4...      |
+/// fields | length: u32 | JSON text |
+///
+/// Driving this through `tokio_util::codec` means partial reads are
+/// simply buffered in a `BytesMut` until a full frame is available,
+/// instead of looping over raw socket calls and `unwrap()`-ing our way
+/// through the bytes by hand.
+pub struct MessageCodec;
+
+impl Encoder<Message> for MessageCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: Message, dst: &mut BytesMut) -> io::Result<()> {
+        // Serialize the message to a JSON string, same as before.
+        let json_text = serde_json::to_string(&msg).unwrap();
+        let json_bytes = json_text.as_bytes();
+        let len: u32 = json_bytes.len() as u32;
+
+        // Make room for the header plus the payload, then write both.
+        dst.reserve(4 + json_bytes.len());
+        dst.put_u32_le(len);
+        dst.put_slice(json_bytes);
+
+        Ok(())
+    }
+}
 
-    // We will create a payload in this format. It starts with a
-    // 4-byte integer, which is the message length of the following
-    // JSON text.
-    //
-    // offset | 0..4        | 4...      |
-    // fields | length: u32 | JSON text |
+impl Decoder for MessageCodec {
+    type Item = Message;
+    type Error = io::Error;
 
-    // Re-interpret the JSON string as bytes.
-    let json_bytes: &[u8] = json_text.as_bytes();
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Message>> {
+        // Reserve at least one byte so the caller always has room to
+        // read more bytes into `src` before asking us to decode
+        // again.
+        src.reserve(1);
 
-    // Get the payload length and create the 4-byte header.
-    let len: usize = json_bytes.len();
-    let len: u32 = len as u32;
-    let len_bytes: [u8; 4] = len.to_le_bytes();
+        // Wait for the 4-byte length header to fully arrive.
+        if src.len() < 4 {
+            return Ok(None);
+        }
 
-    // Send the 4-byte length.
-    send_exact(sock, &len_bytes).await?;
+        let len = u32::from_le_bytes(src[..4].try_into().unwrap()) as usize;
 
-    // Send the JSON bytes.
-    send_exact(sock, json_bytes).await?;
+        // Wait for the whole JSON payload to arrive too. Reserving
+        // the missing bytes up front means the next read is sized to
+        // complete the frame instead of trickling in one byte at a
+        // time.
+        if src.len() < 4 + len {
+            src.reserve(4 + len - src.len());
+            return Ok(None);
+        }
 
-    Ok(())
-}
+        // The full frame is here: drop the header and take ownership
+        // of the payload bytes.
+        src.advance(4);
+        let json_bytes = src.split_to(len);
 
-/// Sends whole buffer to the socket.
-async fn send_exact(sock: &UdpSocket, buf: &[u8]) -> io::Result<()> {
-    // `rest` points to the remaining sub-slice that is not sent yet.
-    let mut rest = buf;
-
-    // Loops until the rest unsent bytes become empty.
-    while !rest.is_empty() {
-        // Send the remaining bytes and returns the actual number of
-        // sent bytes.
-        let count = sock.send(rest).await?;
-
-        // It's a special case when the socket is closed. Here returns
-        // an error because the current function expects that the
-        // whole buffer should be sent.
-        if count == 0 {
-            let err = io::Error::new(io::ErrorKind::ConnectionAborted, "The socket is closed");
-            return Err(err);
-        }
+        let json_text = String::from_utf8(json_bytes.to_vec())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let msg: Message = serde_json::from_str(&json_text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
 
-        // Forward the `rest` by `count` bytes.
-        rest = &rest[count..];
+        Ok(Some(msg))
     }
-
-    Ok(())
 }
 
-/// The async function tries to read a message from the UDP socket.
+/// Sends one message to the opponent over any [`Transport`].
 ///
-/// If one message is successfully received and decoded, it returns
-/// Some(msg). If the socket is closed, it returns None.
-pub async fn recv_msg(sock: &UdpSocket) -> io::Result<Message> {
-    // Here it reads 4 bytes from the socket to learn the length of
-    // the following JSON bytes.
-
-    let mut len_bytes = [0u8; 4]; // Creates a 4-byte buffer.
-    recv_exact(sock, &mut len_bytes).await?; // Fill the bytes in the buffer from the socket.
-
-    // Convert the bytes to an integer.
-    let len: u32 = u32::from_le_bytes(len_bytes);
-    let len = len as usize;
+/// The exact framing is handled by the transport's own `Transport`
+/// impl (built on `MessageCodec` above); this wrapper just exists so
+/// the call sites read the same regardless of which transport is
+/// plugged in.
+pub async fn send_msg<T: Transport>(transport: &T, msg: Message) -> io::Result<()> {
+    transport.send(msg).await
+}
 
-    // Next, read the following JSON bytes.
+/// Receives one message from the opponent over any [`Transport`].
+pub async fn recv_msg<T: Transport>(transport: &T) -> io::Result<Message> {
+    transport.recv().await
+}
 
-    // Creates a buffer to store JSON bytes. Here we use a Vec instead
-    // of an array because the size is determined in runtime.
-    let mut json_bytes = vec![0u8; len];
-    recv_exact(sock, &mut json_bytes).await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::Action;
 
-    // Convert the JSON bytes to a JSON string.
-    let json_text = String::from_utf8(json_bytes).unwrap();
+    #[test]
+    fn round_trips_a_message() {
+        let mut codec = MessageCodec;
+        let mut buf = BytesMut::new();
 
-    // Decode the JSON string into a message.
-    let msg: Message = serde_json::from_str(&json_text).unwrap();
+        codec.encode(Message::Act(Action::Paper), &mut buf).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
 
-    Ok(msg)
-}
+        match decoded {
+            Message::Act(Action::Paper) => {}
+            other => panic!("expected Act(Paper), got {other:?}"),
+        }
+        // The whole frame should have been consumed.
+        assert!(buf.is_empty());
+    }
 
-/// It reads bytes from the socket until the whole buffer is full.
-async fn recv_exact(sock: &UdpSocket, buf: &mut [u8]) -> io::Result<()> {
-    // The `rest` is a sub-slice of `buf`, pointing to the tailing
-    // bytes that are not filled in yet.
-    let mut rest = buf;
-
-    // Loop when the `rest` is not empty.
-    while !rest.is_empty() {
-        // Read bytes from the socket. It returns the number of
-        // received bytes.
-        let count = sock.recv(rest).await?;
-
-        // It is a special case when the socket is closed.  It returns
-        // a error because the function expects that the buffer must
-        // be full-filled, but the socket closes early.
-        if count == 0 {
-            let err = io::Error::new(io::ErrorKind::UnexpectedEof, "The socket is closed");
-            return Err(err);
+    #[test]
+    fn decode_waits_for_the_whole_frame_before_returning_one() {
+        let mut codec = MessageCodec;
+
+        let mut encoded = BytesMut::new();
+        codec
+            .encode(
+                Message::Leave {
+                    name: "alice".to_string(),
+                },
+                &mut encoded,
+            )
+            .unwrap();
+
+        // Feed the encoded frame in one byte at a time: `decode` must
+        // keep returning `Ok(None)`, not error, until the whole
+        // length-prefixed frame has actually arrived.
+        let mut buf = BytesMut::new();
+        let mut decoded = None;
+        for &byte in &encoded {
+            buf.put_u8(byte);
+            decoded = codec.decode(&mut buf).unwrap();
         }
 
-        // Forward the `rest` by `count` bytes. It's done by taking a
-        // sub-slice of itself.
-        rest = &mut rest[count..];
+        match decoded {
+            Some(Message::Leave { name }) => assert_eq!(name, "alice"),
+            other => panic!("expected Leave(\"alice\"), got {other:?}"),
+        }
     }
-
-    Ok(())
 }